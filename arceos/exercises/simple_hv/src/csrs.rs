@@ -0,0 +1,83 @@
+//! Access to the hypervisor-extension CSRs used to enter/exit and interrupt a guest.
+
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::LocalRegisterCopy;
+
+pub mod defs {
+    use tock_registers::register_bitfields;
+
+    register_bitfields![usize,
+        pub hstatus [
+            /// Whether an `sret` returns to guest (VS) or host (HS) mode.
+            spv OFFSET(7) NUMBITS(1) [
+                Host = 0,
+                Guest = 1,
+            ],
+            /// Whether VS-mode memory accessed from HS-mode is treated as user or
+            /// supervisor (used by `hlv`/`hsv*` and by page-fault handling).
+            spvp OFFSET(8) NUMBITS(1) [
+                User = 0,
+                Supervisor = 1,
+            ],
+        ],
+        pub hvip [
+            /// Pending VS-level software interrupt.
+            vssip OFFSET(2) NUMBITS(1) [],
+            /// Pending VS-level timer interrupt.
+            vstip OFFSET(6) NUMBITS(1) [],
+            /// Pending VS-level external interrupt.
+            vseip OFFSET(10) NUMBITS(1) [],
+        ],
+    ];
+}
+
+/// A CSR that can be read and written as a whole, and modified a field at a time.
+pub trait RiscvCsrTrait {
+    fn read_value(&self) -> usize;
+    fn write_value(&self, value: usize);
+
+    fn modify<R: tock_registers::registers::RegisterLongName>(
+        &self,
+        field: tock_registers::fields::FieldValue<usize, R>,
+    ) {
+        let mut value = LocalRegisterCopy::<usize, R>::new(self.read_value());
+        value.modify(field);
+        self.write_value(value.get());
+    }
+}
+
+macro_rules! define_csr {
+    ($struct_name:ident, $csr_name:tt) => {
+        pub struct $struct_name;
+
+        impl RiscvCsrTrait for $struct_name {
+            fn read_value(&self) -> usize {
+                let value: usize;
+                unsafe {
+                    core::arch::asm!(concat!("csrr {0}, ", stringify!($csr_name)), out(reg) value)
+                }
+                value
+            }
+
+            fn write_value(&self, value: usize) {
+                unsafe {
+                    core::arch::asm!(concat!("csrw ", stringify!($csr_name), ", {0}"), in(reg) value)
+                }
+            }
+        }
+    };
+}
+
+define_csr!(HstatusCsr, hstatus);
+define_csr!(HvipCsr, hvip);
+
+/// The hypervisor-extension CSRs, accessed through [`RiscvCsrTrait`].
+pub struct CsrRegs {
+    pub hstatus: HstatusCsr,
+    pub hvip: HvipCsr,
+}
+
+pub static CSR: CsrRegs = CsrRegs {
+    hstatus: HstatusCsr,
+    hvip: HvipCsr,
+};
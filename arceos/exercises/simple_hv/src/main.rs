@@ -10,6 +10,7 @@ extern crate axstd as std;
 extern crate axlog;
 
 mod csrs;
+mod emulate;
 mod loader;
 mod regs;
 mod sbi;
@@ -33,10 +34,19 @@ use vcpu::_run_guest;
 
 const VM_ENTRY: usize = 0x8020_0000;
 
+/// Guest-physical base of the virtual 16550-style UART used to model a simple MMIO device.
+const UART_BASE: usize = 0x1000_0000;
+const UART_SIZE: usize = 0x100;
+/// Offset of the line-status register; bit 5 (THR empty) must read as set or the guest's
+/// driver spins forever waiting to transmit.
+const UART_LSR_OFFSET: usize = 5;
+
 #[cfg_attr(feature = "axstd", no_mangle)]
 fn main() {
     ax_println!("Hypervisor ...");
 
+    emulate::register_mmio(UART_BASE..UART_BASE + UART_SIZE, virtual_uart);
+
     // A new address space for vm.
     let mut uspace = axmm::new_user_aspace().unwrap();
 
@@ -59,6 +69,22 @@ fn main() {
     panic!("Hypervisor ok!");
 }
 
+/// MMIO handler for the virtual UART registered at [`UART_BASE`]: writes to the transmit
+/// register are printed to the host console, and the line-status register always reports
+/// the transmitter as ready.
+fn virtual_uart(addr: usize, _width: usize, value: Option<u64>) -> u64 {
+    let offset = addr - UART_BASE;
+    match value {
+        Some(v) if offset == 0 => {
+            ax_print!("{}", (v as u8) as char);
+            0
+        }
+        Some(_) => 0,
+        None if offset == UART_LSR_OFFSET => 0x60,
+        None => 0,
+    }
+}
+
 fn prepare_vm_pgtable(ept_root: PhysAddr) {
     let hgatp = 8usize << 60 | usize::from(ept_root) >> 12;
     unsafe {
@@ -71,6 +97,8 @@ fn prepare_vm_pgtable(ept_root: PhysAddr) {
 }
 
 fn run_guest(ctx: &mut VmCpuRegisters, uspace: &mut AddrSpace) -> bool {
+    inject_pending_timer(ctx);
+
     unsafe {
         _run_guest(ctx);
     }
@@ -78,12 +106,33 @@ fn run_guest(ctx: &mut VmCpuRegisters, uspace: &mut AddrSpace) -> bool {
     vmexit_handler(ctx, uspace)
 }
 
+/// If the guest's last requested timer deadline has passed, sets the VS-timer pending bit
+/// so it takes its own supervisor timer trap on the next `_run_guest` entry. Otherwise
+/// clears it, so a stale pending bit left over from a deadline the guest has since moved
+/// into the future doesn't keep re-triggering the guest's timer handler.
+fn inject_pending_timer(ctx: &mut VmCpuRegisters) {
+    let now = riscv::register::time::read64();
+    if now >= GUEST_TIMER_DEADLINE.load(core::sync::atomic::Ordering::Relaxed) {
+        ctx.inject_interrupt(vcpu::VsInterrupt::Timer);
+    } else {
+        ctx.clear_interrupt(vcpu::VsInterrupt::Timer);
+    }
+}
+
 #[allow(unreachable_code)]
 fn vmexit_handler(ctx: &mut VmCpuRegisters, uspace: &mut AddrSpace) -> bool {
-    use scause::{Exception, Trap};
+    use scause::{Exception, Interrupt, Trap};
 
     let scause = scause::read();
     match scause.cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            // The host timer we armed on the guest's behalf just fired as an HS-level
+            // trap. Raise the guest's own VS-timer pending bit so it takes a timer trap
+            // on the next `_run_guest` entry, then push the host deadline back out so
+            // this interrupt doesn't immediately re-fire before the guest reprograms it.
+            ctx.inject_interrupt(vcpu::VsInterrupt::Timer);
+            sbi::forward_to_host(sbi::EID_TIME, 0, [u64::MAX as usize, 0, 0, 0, 0, 0]);
+        }
         Trap::Exception(Exception::VirtualSupervisorEnvCall) => {
             let sbi_msg = SbiMessage::from_regs(ctx.guest_regs.gprs.a_regs()).ok();
             ax_println!("VmExit Reason: VSuperEcall: {:?}", sbi_msg);
@@ -98,7 +147,55 @@ fn vmexit_handler(ctx: &mut VmCpuRegisters, uspace: &mut AddrSpace) -> bool {
                         ax_println!("Shutdown vm normally!");
                         return true;
                     }
-                    _ => todo!(),
+                    SbiMessage::PutChar(c) => {
+                        ax_print!("{}", c as char);
+                        ctx.guest_regs.sepc += 4;
+                    }
+                    SbiMessage::PutString { num_bytes, addr } => {
+                        let mut byte = [0u8; 1];
+                        for i in 0..num_bytes {
+                            uspace.read(VirtAddr::from(addr as usize + i), &mut byte);
+                            ax_print!("{}", byte[0] as char);
+                        }
+                        ctx.guest_regs.gprs.set_reg(A0, sbi::SbiReturn::SUCCESS);
+                        ctx.guest_regs.gprs.set_reg(A1, num_bytes);
+                        ctx.guest_regs.sepc += 4;
+                    }
+                    SbiMessage::GetChar => {
+                        // Legacy SBI calls use the single-value convention: the result
+                        // (the character, or -1) comes back in a0, not a1.
+                        let ret = sbi::forward_to_host(sbi::EID_LEGACY_CONSOLE_GETCHAR, 0, [0; 6]);
+                        ctx.guest_regs.gprs.set_reg(A0, ret.error);
+                        ctx.guest_regs.sepc += 4;
+                    }
+                    SbiMessage::SetTimer(deadline) => {
+                        set_guest_timer(deadline);
+                        ctx.guest_regs.sepc += 4;
+                    }
+                    SbiMessage::ProbeExtension(probed_eid) => {
+                        // Extensions we emulate ourselves are answered locally, so a guest
+                        // that probes before using DBCN/TIME/etc. doesn't see the host's
+                        // (irrelevant) support for them instead of ours.
+                        let value = if sbi::is_emulated_extension(probed_eid) {
+                            1
+                        } else {
+                            sbi::forward_to_host(
+                                sbi::EID_BASE,
+                                sbi::BASE_FID_PROBE_EXTENSION,
+                                [probed_eid, 0, 0, 0, 0, 0],
+                            )
+                            .value
+                        };
+                        ctx.guest_regs.gprs.set_reg(A0, sbi::SbiReturn::SUCCESS);
+                        ctx.guest_regs.gprs.set_reg(A1, value);
+                        ctx.guest_regs.sepc += 4;
+                    }
+                    SbiMessage::Forward { eid, fid, args } => {
+                        let ret = sbi::forward_to_host(eid, fid, args);
+                        ctx.guest_regs.gprs.set_reg(A0, ret.error);
+                        ctx.guest_regs.gprs.set_reg(A1, ret.value);
+                        ctx.guest_regs.sepc += 4;
+                    }
                 }
             } else {
                 panic!("bad sbi message! ");
@@ -119,13 +216,18 @@ fn vmexit_handler(ctx: &mut VmCpuRegisters, uspace: &mut AddrSpace) -> bool {
             ctx.guest_regs.gprs.set_reg(A1, 0x1234);
         }
         Trap::Exception(Exception::LoadGuestPageFault) => {
+            let addr = VirtAddr::from(stval::read());
+
+            if emulate::emulate_mmio(ctx, uspace, addr) {
+                return false;
+            }
+
             warn!(
                 "LoadGuestPageFault: stval{:#x} sepc: {:#x}",
                 stval::read(),
                 ctx.guest_regs.sepc
             );
 
-            let addr = VirtAddr::from(stval::read());
             uspace.map_alloc(
                 addr.align_down_4k(),
                 PAGE_SIZE_4K,
@@ -144,6 +246,26 @@ fn vmexit_handler(ctx: &mut VmCpuRegisters, uspace: &mut AddrSpace) -> bool {
             uspace.write(addr, buf);
 
         }
+        Trap::Exception(Exception::StoreGuestPageFault) => {
+            let addr = VirtAddr::from(stval::read());
+
+            if emulate::emulate_mmio(ctx, uspace, addr) {
+                return false;
+            }
+
+            warn!(
+                "StoreGuestPageFault: stval{:#x} sepc: {:#x}",
+                stval::read(),
+                ctx.guest_regs.sepc
+            );
+
+            uspace.map_alloc(
+                addr.align_down_4k(),
+                PAGE_SIZE_4K,
+                MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+                true,
+            );
+        }
         _ => {
             panic!(
                 "Unhandled trap: {:?}, sepc: {:#x}, stval: {:#x}",
@@ -156,6 +278,17 @@ fn vmexit_handler(ctx: &mut VmCpuRegisters, uspace: &mut AddrSpace) -> bool {
     false
 }
 
+/// Deadline (in guest timebase ticks) the guest last requested via `SBI.TIME.set_timer`.
+static GUEST_TIMER_DEADLINE: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(u64::MAX);
+
+/// Services a guest `TIME.set_timer` call: programs the host timer for `deadline` and
+/// remembers it so `inject_pending_timer` can set or clear the guest's VS-timer pending
+/// bit before each guest entry.
+fn set_guest_timer(deadline: u64) {
+    GUEST_TIMER_DEADLINE.store(deadline, core::sync::atomic::Ordering::Relaxed);
+    sbi::forward_to_host(sbi::EID_TIME, 0, [deadline as usize, 0, 0, 0, 0, 0]);
+}
+
 fn prepare_guest_context(ctx: &mut VmCpuRegisters) {
     // Set hstatus
     let mut hstatus =
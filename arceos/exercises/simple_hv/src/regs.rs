@@ -0,0 +1,93 @@
+//! Guest general-purpose register state and accessors.
+
+/// Index of a RISC-V general-purpose register within [`GeneralPurposeRegisters`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(usize)]
+pub enum GprIndex {
+    Zero = 0,
+    RA,
+    SP,
+    GP,
+    TP,
+    T0,
+    T1,
+    T2,
+    S0,
+    S1,
+    A0,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6,
+    A7,
+    S2,
+    S3,
+    S4,
+    S5,
+    S6,
+    S7,
+    S8,
+    S9,
+    S10,
+    S11,
+    T3,
+    T4,
+    T5,
+    T6,
+}
+
+impl GprIndex {
+    /// Returns the register's index into the `a0..a7` argument registers, if it is one.
+    pub fn a_num(&self) -> Option<usize> {
+        let index = *self as usize;
+        if (GprIndex::A0 as usize..=GprIndex::A7 as usize).contains(&index) {
+            Some(index - GprIndex::A0 as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Maps a raw 5-bit `x`-register number (as found in an instruction encoding) to a
+    /// `GprIndex`. Panics if `index` is out of range.
+    pub fn from_raw(index: usize) -> Self {
+        use GprIndex::*;
+        const TABLE: [GprIndex; 32] = [
+            Zero, RA, SP, GP, TP, T0, T1, T2, S0, S1, A0, A1, A2, A3, A4, A5, A6, A7, S2, S3, S4,
+            S5, S6, S7, S8, S9, S10, S11, T3, T4, T5, T6,
+        ];
+        TABLE[index]
+    }
+}
+
+/// The general-purpose registers of a guest vCPU.
+#[derive(Default, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct GeneralPurposeRegisters([usize; 32]);
+
+impl GeneralPurposeRegisters {
+    /// Returns the value of register `index`.
+    pub fn reg(&self, index: GprIndex) -> usize {
+        self.0[index as usize]
+    }
+
+    /// Sets register `index` to `value`. `x0` is hardwired to zero in hardware, so a write
+    /// to `GprIndex::Zero` is silently discarded rather than polluting its shadow.
+    pub fn set_reg(&mut self, index: GprIndex, value: usize) {
+        if index == GprIndex::Zero {
+            return;
+        }
+        self.0[index as usize] = value;
+    }
+
+    /// Returns the `a0..a7` argument registers, as used by the SBI calling convention.
+    pub fn a_regs(&self) -> &[usize] {
+        &self.0[GprIndex::A0 as usize..=GprIndex::A7 as usize]
+    }
+
+    /// Returns the `a0..a7` argument registers, mutably.
+    pub fn a_regs_mut(&mut self) -> &mut [usize] {
+        &mut self.0[GprIndex::A0 as usize..=GprIndex::A7 as usize]
+    }
+}
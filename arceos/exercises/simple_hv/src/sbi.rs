@@ -0,0 +1,144 @@
+//! Decoding of SBI calls trapped from VS-mode (`ecall` with `VirtualSupervisorEnvCall`).
+//!
+//! A guest makes SBI calls the same way it would on bare metal: extension ID in `a7`,
+//! function ID in `a6`, arguments in `a0..a5`. We decode just enough of each extension to
+//! either service it ourselves (console, timer) or forward it verbatim to the host's own
+//! `sbi` implementation.
+
+/// Base extension ID (`0x10`).
+pub const EID_BASE: usize = 0x10;
+/// Base `probe_extension(extension_id)`: whether `extension_id` is available.
+pub const BASE_FID_PROBE_EXTENSION: usize = 3;
+/// Legacy console-putchar extension.
+pub const EID_LEGACY_CONSOLE_PUTCHAR: usize = 0x1;
+/// Legacy console-getchar extension.
+pub const EID_LEGACY_CONSOLE_GETCHAR: usize = 0x2;
+/// Debug console ("DBCN") extension.
+pub const EID_DBCN: usize = 0x4442434E;
+/// DBCN `console_write(num_bytes, base_addr_lo, base_addr_hi)`: writes a guest-memory
+/// buffer to the console.
+pub const DBCN_CONSOLE_WRITE: usize = 0;
+/// DBCN `console_write_byte(byte)`: writes a single byte to the console.
+pub const DBCN_CONSOLE_WRITE_BYTE: usize = 2;
+/// Timer ("TIME") extension.
+pub const EID_TIME: usize = 0x54494D45;
+/// IPI ("sPI") extension.
+pub const EID_IPI: usize = 0x735049;
+/// System reset ("SRST") extension.
+pub const EID_SRST: usize = 0x53525354;
+
+/// Result of a serviced SBI call, written back into guest `a0`/`a1`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SbiReturn {
+    pub error: usize,
+    pub value: usize,
+}
+
+impl SbiReturn {
+    pub const SUCCESS: usize = 0;
+
+    pub fn success(value: usize) -> Self {
+        Self {
+            error: Self::SUCCESS,
+            value,
+        }
+    }
+}
+
+/// The reset type requested via the SRST extension.
+#[derive(Copy, Clone, Debug)]
+pub struct ResetFunction {
+    pub reset_type: usize,
+    pub reset_reason: usize,
+}
+
+/// A decoded SBI call, or the raw extension/function IDs for anything we pass straight
+/// through to the host.
+#[derive(Copy, Clone, Debug)]
+pub enum SbiMessage {
+    /// `SRST.system_reset`.
+    Reset(ResetFunction),
+    /// Legacy or DBCN `console_write_byte` putchar; carries the byte to print.
+    PutChar(u8),
+    /// DBCN `console_write`; carries the buffer length and its guest-physical address.
+    PutString { num_bytes: usize, addr: u64 },
+    /// Legacy console getchar.
+    GetChar,
+    /// `TIME.set_timer`; carries the absolute deadline in guest timebase ticks.
+    SetTimer(u64),
+    /// Base `probe_extension`; carries the extension ID being probed. Extensions the
+    /// hypervisor itself emulates are answered locally; any other probe is forwarded to
+    /// the host.
+    ProbeExtension(usize),
+    /// Anything else we don't need to inspect: forwarded to the host `sbi` crate verbatim.
+    Forward { eid: usize, fid: usize, args: [usize; 6] },
+}
+
+/// Errors decoding a guest SBI call.
+#[derive(Copy, Clone, Debug)]
+pub enum SbiError {
+    /// Not enough argument registers were supplied to decode this extension/function.
+    MalformedMessage,
+}
+
+/// Issues an `ecall` to the host's own SBI implementation, forwarding `eid`/`fid`/`args`
+/// as-is and returning whatever `(error, value)` pair comes back in `a0`/`a1`.
+///
+/// Used for any guest SBI call we don't service ourselves (HSM, RFENCE, Base probes,
+/// IPI, ...): the hypervisor just re-issues the call one privilege level up.
+pub fn forward_to_host(eid: usize, fid: usize, args: [usize; 6]) -> SbiReturn {
+    let (error, value);
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            in("a7") eid,
+            in("a6") fid,
+            inlateout("a0") args[0] => error,
+            inlateout("a1") args[1] => value,
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a5") args[5],
+        );
+    }
+    SbiReturn { error, value }
+}
+
+/// Whether `eid` names an extension this hypervisor services itself, rather than
+/// forwarding to the host. Used to answer `probe_extension` truthfully.
+pub fn is_emulated_extension(eid: usize) -> bool {
+    matches!(
+        eid,
+        EID_SRST | EID_LEGACY_CONSOLE_PUTCHAR | EID_LEGACY_CONSOLE_GETCHAR | EID_DBCN | EID_TIME
+    )
+}
+
+impl SbiMessage {
+    /// Decodes a guest SBI call from its `a0..a7` argument registers (`a7` is the
+    /// extension ID, `a6` the function ID).
+    pub fn from_regs(a_regs: &[usize]) -> Result<Self, SbiError> {
+        if a_regs.len() < 8 {
+            return Err(SbiError::MalformedMessage);
+        }
+        let eid = a_regs[7];
+        let fid = a_regs[6];
+        let args = [a_regs[0], a_regs[1], a_regs[2], a_regs[3], a_regs[4], a_regs[5]];
+
+        Ok(match eid {
+            EID_SRST => SbiMessage::Reset(ResetFunction {
+                reset_type: args[0],
+                reset_reason: args[1],
+            }),
+            EID_LEGACY_CONSOLE_PUTCHAR => SbiMessage::PutChar(args[0] as u8),
+            EID_LEGACY_CONSOLE_GETCHAR => SbiMessage::GetChar,
+            EID_DBCN if fid == DBCN_CONSOLE_WRITE_BYTE => SbiMessage::PutChar(args[0] as u8),
+            EID_DBCN if fid == DBCN_CONSOLE_WRITE => SbiMessage::PutString {
+                num_bytes: args[0],
+                addr: ((args[2] as u64) << 32) | args[1] as u64,
+            },
+            EID_TIME => SbiMessage::SetTimer(args[0] as u64),
+            EID_BASE if fid == BASE_FID_PROBE_EXTENSION => SbiMessage::ProbeExtension(args[0]),
+            _ => SbiMessage::Forward { eid, fid, args },
+        })
+    }
+}
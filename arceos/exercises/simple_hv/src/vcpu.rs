@@ -0,0 +1,76 @@
+//! Per-vCPU register state and guest entry/exit.
+
+use crate::csrs::defs::hvip;
+use crate::csrs::{RiscvCsrTrait, CSR};
+use crate::regs::GeneralPurposeRegisters;
+
+/// A VS-level interrupt that can be injected into the guest via `hvip`.
+#[derive(Copy, Clone, Debug)]
+pub enum VsInterrupt {
+    Software,
+    Timer,
+    External,
+}
+
+/// Guest-visible CPU state, saved/restored around every `_run_guest` round trip.
+#[derive(Default, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct GuestCpuState {
+    pub gprs: GeneralPurposeRegisters,
+    pub sstatus: usize,
+    pub hstatus: usize,
+    pub sepc: usize,
+}
+
+/// Host CPU state that must be preserved across a guest entry, restored on vmexit.
+#[derive(Default, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct HostCpuState {
+    pub gprs: GeneralPurposeRegisters,
+    pub sstatus: usize,
+    pub sepc: usize,
+}
+
+/// Full register file for a vCPU: what we restore into hardware before `sret`-ing into
+/// the guest, and what we save back out on the following vmexit.
+#[derive(Default, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct VmCpuRegisters {
+    pub guest_regs: GuestCpuState,
+    pub host_regs: HostCpuState,
+}
+
+impl VmCpuRegisters {
+    /// Sets the pending bit for `cause` in `hvip`, so the guest observes a pending VS-level
+    /// interrupt the next time it's resumed with `_run_guest`.
+    pub fn inject_interrupt(&mut self, cause: VsInterrupt) {
+        let field = match cause {
+            VsInterrupt::Software => hvip::vssip::SET,
+            VsInterrupt::Timer => hvip::vstip::SET,
+            VsInterrupt::External => hvip::vseip::SET,
+        };
+        CSR.hvip.modify(field);
+    }
+
+    /// Clears the pending bit for `cause` in `hvip`. Used once the condition that raised it
+    /// (e.g. a now-stale timer deadline) no longer holds, so the guest doesn't keep
+    /// re-taking the same interrupt.
+    pub fn clear_interrupt(&mut self, cause: VsInterrupt) {
+        let field = match cause {
+            VsInterrupt::Software => hvip::vssip::CLEAR,
+            VsInterrupt::Timer => hvip::vstip::CLEAR,
+            VsInterrupt::External => hvip::vseip::CLEAR,
+        };
+        CSR.hvip.modify(field);
+    }
+}
+
+extern "C" {
+    /// Enters guest mode with the register file in `ctx`, returning on the next vmexit.
+    ///
+    /// # Safety
+    ///
+    /// `ctx` must describe a valid guest register state and `hgatp`/EPT must already be
+    /// configured for the calling hart.
+    pub fn _run_guest(ctx: &mut VmCpuRegisters);
+}
@@ -1,6 +1,10 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
-use allocator::{BaseAllocator, ByteAllocator, PageAllocator};
+use allocator::{AllocError, BaseAllocator, ByteAllocator, PageAllocator};
+
+mod untyped;
+
+pub use untyped::{retype, ObjectSlot, RetypeError, Untyped};
 
 /// Early memory allocator
 /// Use it before formal bytes-allocator and pages-allocator can work!
@@ -54,11 +58,18 @@ impl<const PAGE_SIZE: usize> BaseAllocator for EarlyAllocator<PAGE_SIZE> {
 
 impl<const PAGE_SIZE: usize> ByteAllocator for EarlyAllocator<PAGE_SIZE> {
     fn alloc(&mut self, layout: core::alloc::Layout) -> allocator::AllocResult<core::ptr::NonNull<u8>> {
-        let current_bytes_pos = self.bytes_pos;
-        self.bytes_pos += layout.size();
+        let aligned_pos = (self.bytes_pos + layout.align() - 1) & !(layout.align() - 1);
+        let new_bytes_pos = aligned_pos
+            .checked_add(layout.size())
+            .ok_or(AllocError::NoMemory)?;
+        if new_bytes_pos > self.pages_pos {
+            return Err(AllocError::NoMemory);
+        }
+
+        self.bytes_pos = new_bytes_pos;
         self.count += 1;
 
-        Ok(core::ptr::NonNull::new(current_bytes_pos as *mut u8).unwrap())
+        Ok(core::ptr::NonNull::new(aligned_pos as *mut u8).unwrap())
     }
 
     fn dealloc(&mut self,_pos: core::ptr::NonNull<u8>, _layout: core::alloc::Layout) {
@@ -86,7 +97,19 @@ impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
 
     fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> allocator::AllocResult<usize> {
         let align = 1 << align_pow2;
-        self.pages_pos = (self.pages_pos - num_pages * PAGE_SIZE) & !(align - 1);
+        let size = num_pages
+            .checked_mul(PAGE_SIZE)
+            .ok_or(AllocError::NoMemory)?;
+        let new_pages_pos = self
+            .pages_pos
+            .checked_sub(size)
+            .ok_or(AllocError::NoMemory)?
+            & !(align - 1);
+        if new_pages_pos < self.bytes_pos {
+            return Err(AllocError::NoMemory);
+        }
+
+        self.pages_pos = new_pages_pos;
         Ok(self.pages_pos)
     }
 
@@ -106,3 +129,105 @@ impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
         (self.pages_pos - self.bytes_pos) / PAGE_SIZE
     }
 }
+
+impl<const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
+    /// Carves pages from the backward region exactly like [`PageAllocator::alloc_pages`],
+    /// but zeroes them before returning. Page tables and freshly mapped guest frames must
+    /// start zeroed; callers that immediately overwrite the memory can use the unzeroed
+    /// `alloc_pages` instead.
+    pub fn alloc_pages_zeroed(
+        &mut self,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> allocator::AllocResult<usize> {
+        let pos = self.alloc_pages(num_pages, align_pow2)?;
+        unsafe {
+            core::ptr::write_bytes(pos as *mut u8, 0, num_pages * PAGE_SIZE);
+        }
+        Ok(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::alloc::Layout;
+
+    fn new_allocator<const PAGE_SIZE: usize>(backing: &mut [u8]) -> EarlyAllocator<PAGE_SIZE> {
+        let mut alloc = EarlyAllocator::<PAGE_SIZE>::new();
+        alloc.init(backing.as_mut_ptr() as usize, backing.len());
+        alloc
+    }
+
+    #[test]
+    fn alloc_rounds_up_to_the_requested_alignment() {
+        let mut backing = [0u8; 0x100];
+        let mut alloc = new_allocator::<0x100>(&mut backing);
+
+        // Throw off natural alignment first.
+        alloc.alloc(Layout::from_size_align(1, 1).unwrap()).unwrap();
+
+        let ptr = alloc.alloc(Layout::from_size_align(8, 16).unwrap()).unwrap();
+        assert_eq!(ptr.as_ptr() as usize % 16, 0);
+    }
+
+    #[test]
+    fn alloc_refuses_once_it_would_cross_into_the_pages_region() {
+        let mut backing = [0u8; 0x100];
+        let mut alloc = new_allocator::<0x100>(&mut backing);
+
+        // Carve the whole region out as pages, leaving nothing for the byte allocator.
+        alloc.alloc_pages(1, 0).unwrap();
+
+        assert!(matches!(
+            alloc.alloc(Layout::from_size_align(1, 1).unwrap()),
+            Err(AllocError::NoMemory)
+        ));
+    }
+
+    #[test]
+    fn alloc_succeeds_right_up_to_the_boundary() {
+        let mut backing = [0u8; 0x100];
+        let mut alloc = new_allocator::<0x100>(&mut backing);
+
+        // Exactly fills the region; one more byte must fail.
+        alloc
+            .alloc(Layout::from_size_align(0x100, 1).unwrap())
+            .unwrap();
+        assert!(matches!(
+            alloc.alloc(Layout::from_size_align(1, 1).unwrap()),
+            Err(AllocError::NoMemory)
+        ));
+    }
+
+    #[test]
+    fn alloc_pages_refuses_once_it_would_cross_into_the_bytes_region() {
+        let mut backing = [0u8; 0x100];
+        let mut alloc = new_allocator::<0x100>(&mut backing);
+
+        // Carve the whole region out as bytes, leaving nothing for the page allocator.
+        alloc.alloc(Layout::from_size_align(0x100, 1).unwrap()).unwrap();
+
+        assert!(matches!(alloc.alloc_pages(1, 0), Err(AllocError::NoMemory)));
+    }
+
+    #[test]
+    fn alloc_pages_succeeds_right_up_to_the_boundary() {
+        let mut backing = [0u8; 0x200];
+        let mut alloc = new_allocator::<0x100>(&mut backing);
+
+        // Exactly fills the region; one more page must fail.
+        alloc.alloc_pages(2, 0).unwrap();
+        assert!(matches!(alloc.alloc_pages(1, 0), Err(AllocError::NoMemory)));
+    }
+
+    #[test]
+    fn alloc_pages_zeroed_zeroes_the_returned_range() {
+        let mut backing = [0xAAu8; 0x200];
+        let mut alloc = new_allocator::<0x100>(&mut backing);
+
+        let pos = alloc.alloc_pages_zeroed(1, 0).unwrap();
+        let bytes = unsafe { core::slice::from_raw_parts(pos as *const u8, 0x100) };
+        assert!(bytes.iter().all(|&b| b == 0));
+    }
+}
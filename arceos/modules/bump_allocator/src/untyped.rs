@@ -0,0 +1,205 @@
+//! A seL4-style untyped-memory capability allocator layered on [`EarlyAllocator`].
+//!
+//! Before the real byte/page allocators come online, labs can carve deterministic,
+//! auditable objects (page tables, TCB-like structs, frames) out of a handful of
+//! "untyped" regions via [`retype`], rather than reaching for ad-hoc `alloc_pages` calls.
+
+use core::ops::Range;
+
+use allocator::{AllocResult, PageAllocator};
+
+use crate::EarlyAllocator;
+
+/// An untyped region of memory: `2^size_bits` bytes starting at a naturally-aligned
+/// `base`. Objects are retyped out of it bump-style, within this node only, and are
+/// never reclaimed.
+#[derive(Copy, Clone, Debug)]
+pub struct Untyped {
+    base: usize,
+    size_bits: u32,
+    /// Offset of the next free byte within this untyped, relative to `base`.
+    watermark: usize,
+}
+
+impl Untyped {
+    /// Describes a new untyped node covering `[base, base + 2^size_bits)`.
+    ///
+    /// `base` must be naturally aligned to `2^size_bits`.
+    pub const fn new(base: usize, size_bits: u32) -> Self {
+        debug_assert!(base & ((1 << size_bits) - 1) == 0);
+        Self {
+            base,
+            size_bits,
+            watermark: 0,
+        }
+    }
+
+    /// Carves a new untyped node of `2^size_bits` bytes out of `alloc`'s page region.
+    pub fn from_allocator<const PAGE_SIZE: usize>(
+        alloc: &mut EarlyAllocator<PAGE_SIZE>,
+        size_bits: u32,
+    ) -> AllocResult<Self> {
+        let size = 1usize << size_bits;
+        let base = alloc.alloc_pages((size + PAGE_SIZE - 1) / PAGE_SIZE, size_bits as usize)?;
+        Ok(Self::new(base, size_bits))
+    }
+
+    pub const fn size(&self) -> usize {
+        1 << self.size_bits
+    }
+
+    pub const fn range(&self) -> Range<usize> {
+        self.base..self.base + self.size()
+    }
+}
+
+/// A single retyped object: `2^object_bits` bytes at `addr`, carved from some [`Untyped`].
+#[derive(Copy, Clone, Debug)]
+pub struct ObjectSlot {
+    pub addr: usize,
+    pub object_bits: u32,
+}
+
+/// Errors retyping an untyped region into objects.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RetypeError {
+    /// `untyped` doesn't have `count * 2^object_bits` bytes left.
+    OutOfSpace,
+    /// `slots` isn't big enough to record all `count` resulting objects.
+    TooManySlots,
+}
+
+/// Carves `count` naturally-aligned `2^object_bits` objects out of `untyped`, advancing its
+/// watermark, and records each resulting slot into `slots`.
+///
+/// Alignment always equals object size, and a child object can never escape `untyped`'s own
+/// range: both are enforced by construction rather than checked after the fact.
+pub fn retype(
+    untyped: &mut Untyped,
+    object_bits: u32,
+    count: usize,
+    slots: &mut [ObjectSlot],
+) -> Result<usize, RetypeError> {
+    if count > slots.len() {
+        return Err(RetypeError::TooManySlots);
+    }
+
+    let object_size = 1usize << object_bits;
+    // Alignment equals object size: round the watermark up to the next object boundary
+    // before carving out of it.
+    let aligned_watermark = (untyped.watermark + object_size - 1) & !(object_size - 1);
+    let total = object_size
+        .checked_mul(count)
+        .ok_or(RetypeError::OutOfSpace)?;
+    let new_watermark = aligned_watermark
+        .checked_add(total)
+        .ok_or(RetypeError::OutOfSpace)?;
+    if new_watermark > untyped.size() {
+        return Err(RetypeError::OutOfSpace);
+    }
+
+    for (i, slot) in slots.iter_mut().take(count).enumerate() {
+        *slot = ObjectSlot {
+            addr: untyped.base + aligned_watermark + i * object_size,
+            object_bits,
+        };
+    }
+
+    untyped.watermark = new_watermark;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retype_carves_naturally_aligned_slots_in_order() {
+        let mut untyped = Untyped::new(0x1000, 8); // 256 bytes
+        let mut slots = [ObjectSlot {
+            addr: 0,
+            object_bits: 0,
+        }; 4];
+
+        let count = retype(&mut untyped, 4, 4, &mut slots).unwrap(); // 4 objects of 16 bytes
+        assert_eq!(count, 4);
+        for (i, slot) in slots.iter().enumerate() {
+            assert_eq!(slot.addr, 0x1000 + i * 16);
+            assert_eq!(slot.object_bits, 4);
+            // Alignment always equals object size.
+            assert_eq!(slot.addr % 16, 0);
+        }
+    }
+
+    #[test]
+    fn retype_never_lets_a_child_escape_the_parent_range() {
+        let mut untyped = Untyped::new(0x1000, 8); // 256 bytes
+        let mut slots = [ObjectSlot {
+            addr: 0,
+            object_bits: 0,
+        }; 16];
+
+        let count = retype(&mut untyped, 4, 16, &mut slots).unwrap(); // exactly fills it
+        assert_eq!(count, 16);
+        for slot in &slots {
+            assert!(untyped.range().contains(&slot.addr));
+            assert!(slot.addr + (1 << slot.object_bits) <= untyped.range().end);
+        }
+    }
+
+    #[test]
+    fn retype_is_bump_only_and_never_reclaims() {
+        let mut untyped = Untyped::new(0x1000, 8); // 256 bytes
+        let mut slots = [ObjectSlot {
+            addr: 0,
+            object_bits: 0,
+        }; 8];
+
+        retype(&mut untyped, 4, 8, &mut slots[..8]).unwrap(); // first 128 bytes
+        let first_round_watermark = untyped.watermark;
+        let mut more_slots = [ObjectSlot {
+            addr: 0,
+            object_bits: 0,
+        }; 8];
+        retype(&mut untyped, 4, 8, &mut more_slots).unwrap(); // next 128 bytes
+
+        // The second round starts exactly where the first left off: nothing was reclaimed,
+        // and no object from the first round is revisited.
+        assert_eq!(more_slots[0].addr, untyped.base + first_round_watermark);
+        for (a, b) in slots.iter().zip(more_slots.iter()) {
+            assert_ne!(a.addr, b.addr);
+        }
+    }
+
+    #[test]
+    fn retype_fails_with_out_of_space_when_the_untyped_is_too_small() {
+        let mut untyped = Untyped::new(0x1000, 8); // 256 bytes
+        let mut slots = [ObjectSlot {
+            addr: 0,
+            object_bits: 0,
+        }; 17];
+
+        // 17 objects of 16 bytes is 272 bytes: one object too many.
+        assert_eq!(
+            retype(&mut untyped, 4, 17, &mut slots),
+            Err(RetypeError::OutOfSpace)
+        );
+        // A failed retype must not perturb the watermark.
+        assert_eq!(untyped.watermark, 0);
+    }
+
+    #[test]
+    fn retype_fails_with_too_many_slots_when_the_buffer_is_too_small() {
+        let mut untyped = Untyped::new(0x1000, 8);
+        let mut slots = [ObjectSlot {
+            addr: 0,
+            object_bits: 0,
+        }; 2];
+
+        assert_eq!(
+            retype(&mut untyped, 4, 3, &mut slots),
+            Err(RetypeError::TooManySlots)
+        );
+        assert_eq!(untyped.watermark, 0);
+    }
+}